@@ -5,65 +5,347 @@ use env_logger;
 use log;
 use pyo3::prelude::*;
 use rayon::prelude::*;
-use std::cmp::Ordering;
+use serde::{Deserialize, Serialize};
+use serde_json;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
 use std::result::Result;
-use std::sync::Arc;
 
+// Keep the last few versions of a profile's on-disk template cache so that
+// re-profiling a model produces a new version rather than clobbering one
+// another run might still be reading.
+const MAX_CACHED_TEMPLATE_VERSIONS: usize = 5;
+
+/// A class of device available to host pipeline stages, e.g. an "A100" node
+/// versus a "V100" node. `compute_multiplier` scales a stage's latency when
+/// it is placed on a device of this class (1.0 is the reference speed, >1.0
+/// is slower, <1.0 is faster), `memory_budget` is the most memory a single
+/// device of this class can host, and `count` is how many such devices are
+/// available in the pool.
+#[pyclass]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeviceClass {
+    #[pyo3(get, set)]
+    pub compute_multiplier: f64,
+    #[pyo3(get, set)]
+    pub memory_budget: u64,
+    #[pyo3(get, set)]
+    pub count: u32,
+}
+
+#[pymethods]
+impl DeviceClass {
+    #[new]
+    pub fn new(compute_multiplier: f64, memory_budget: u64, count: u32) -> Self {
+        DeviceClass {
+            compute_multiplier,
+            memory_budget,
+            count,
+        }
+    }
+}
+
+/// A stage's layers together with the device class it has been placed on.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct StageAssignment {
+    #[pyo3(get, set)]
+    pub layers: Vec<String>,
+    #[pyo3(get, set)]
+    pub device_class: usize,
+}
+
+#[pymethods]
+impl StageAssignment {
+    #[new]
+    pub fn new(layers: Vec<String>, device_class: usize) -> Self {
+        StageAssignment {
+            layers,
+            device_class,
+        }
+    }
+}
+
+// A feasible pipeline for some (num_stages, i, j) subproblem, together with
+// the per-device-class usage it consumes. Everything needed to answer
+// `get_pipeline_template`/`to_dot` is tracked directly (stage boundaries and
+// per-stage latency) rather than through the foreign `PipelineExecutionResult`,
+// so that a whole `execution_result_cache` can be serialized to and restored
+// from the on-disk template cache.
+#[derive(Clone, Serialize, Deserialize)]
+struct DeviceAwareResult {
+    // Layer ranges `[start, end)` of each stage, left to right.
+    stage_boundaries: Vec<(usize, usize)>,
+    // Device class index assigned to each stage, left to right.
+    stage_device_classes: Vec<usize>,
+    // Un-scaled `StageExecutionResult::latency()` of each stage, left to right.
+    stage_latencies: Vec<f64>,
+    // Number of devices of each class (indexed like `device_classes`) this
+    // candidate consumes.
+    device_usage: Vec<u32>,
+    effective_latency: f64,
+}
+
+impl DeviceAwareResult {
+    fn modules_per_stage(
+        &self,
+        layer_execution_results: &[LayerExecutionResult],
+    ) -> Vec<Vec<String>> {
+        self.stage_boundaries
+            .iter()
+            .map(|(start, end)| {
+                layer_execution_results[*start..*end]
+                    .iter()
+                    .map(|layer| layer.name.clone())
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+// On-disk representation of `execution_result_cache`, versioned per profile
+// file so that re-profiling a model doesn't clobber a cache another process
+// might still be reading.
+#[derive(Serialize, Deserialize)]
+struct PersistedTemplateCache {
+    // Hash of the profile CSV contents, `max_num_nodes`, the device-class pool
+    // and the activation memory overhead (see `profile_hash`), used to decide
+    // whether a cached plan is still valid for the current profile.
+    profile_hash: String,
+    max_num_nodes: u32,
+    num_layers: usize,
+    device_classes: Vec<DeviceClass>,
+    entries: Vec<PersistedCacheEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersistedCacheEntry {
+    key: (u32, usize, usize),
+    result: Result<Vec<DeviceAwareResult>, String>,
+}
+
+/// Metadata about one version of a template cache on disk, as returned by
+/// `PipelineTemplateGenerator::list_cached_versions`. This is also the
+/// on-disk representation of a version's lightweight `.meta.json` sidecar,
+/// which lets version lookups avoid parsing the full (potentially huge)
+/// `PersistedTemplateCache` payload just to compare hashes and node counts.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CachedVersionInfo {
+    pub version: u32,
+    pub profile_hash: String,
+    pub max_num_nodes: u32,
+}
+
+// Escapes `"` and `\` so arbitrary profiled layer/module names can be
+// embedded as Graphviz DOT quoted strings without breaking the syntax.
+fn dot_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn usage_fits_pool(usage: &[u32], device_classes: &[DeviceClass]) -> bool {
+    usage
+        .iter()
+        .zip(device_classes.iter())
+        .all(|(used, class)| *used <= class.count)
+}
+
+// Keep only the best (lowest latency) candidate per distinct device usage,
+// since candidates with the same usage but higher latency can never win.
+fn best_per_device_usage(candidates: Vec<DeviceAwareResult>) -> Vec<DeviceAwareResult> {
+    let mut by_usage: HashMap<Vec<u32>, DeviceAwareResult> = HashMap::new();
+    for candidate in candidates {
+        by_usage
+            .entry(candidate.device_usage.clone())
+            .and_modify(|existing| {
+                if candidate.effective_latency < existing.effective_latency {
+                    *existing = candidate.clone();
+                }
+            })
+            .or_insert(candidate);
+    }
+    by_usage.into_values().collect()
+}
+
+// Drops any candidate whose device usage and latency are both weakly worse
+// than another candidate's, i.e. keeps only the Pareto frontier over
+// `(device_usage, effective_latency)`. A dominated candidate can never be
+// selected by a merge further up the DP: any pool budget that could realize
+// it could also realize the dominating candidate, at no worse latency. This
+// keeps the number of candidates carried per `(num_stages, i, j)` bounded by
+// the Pareto frontier's size instead of the full cross product of
+// device-usage combinations, which otherwise grows combinatorially with the
+// number of device classes.
+fn prune_dominated(candidates: Vec<DeviceAwareResult>) -> Vec<DeviceAwareResult> {
+    let mut keep = vec![true; candidates.len()];
+    for i in 0..candidates.len() {
+        for j in 0..candidates.len() {
+            if i == j || !keep[i] {
+                continue;
+            }
+            let dominates = candidates[j]
+                .device_usage
+                .iter()
+                .zip(candidates[i].device_usage.iter())
+                .all(|(b, a)| b <= a)
+                && candidates[j].effective_latency <= candidates[i].effective_latency
+                && (candidates[j].device_usage != candidates[i].device_usage
+                    || candidates[j].effective_latency < candidates[i].effective_latency);
+            if dominates {
+                keep[i] = false;
+            }
+        }
+    }
+    candidates
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(candidate, kept)| kept.then_some(candidate))
+        .collect()
+}
+
+#[pyclass]
 pub struct PipelineTemplateGenerator {
+    model_name: String,
+    tag: String,
     layer_execution_results: Vec<LayerExecutionResult>,
-    // Key: (layer_start_index, layer_end_index)
-    stage_execution_results: DashMap<(usize, usize), Arc<StageExecutionResult>>,
     // Key: (num_stages, layer_start_index, layer_end_index)
-    execution_result_cache: DashMap<(u32, usize, usize), Result<PipelineExecutionResult, String>>,
+    // Value: one entry per distinct device-class usage that is feasible for this subproblem.
+    execution_result_cache: DashMap<(u32, usize, usize), Result<Vec<DeviceAwareResult>, String>>,
+    // Highest number of nodes `divide_and_conquer` has computed templates for.
+    max_num_nodes: u32,
+    device_classes: Vec<DeviceClass>,
+    // Whether `divide_and_conquer` has already populated `execution_result_cache`,
+    // either by computing it or by loading it from the on-disk template cache.
+    computed: bool,
 }
 
 impl PipelineTemplateGenerator {
     pub fn new(model_name: &str, tag: &str) -> Self {
         PipelineTemplateGenerator {
+            model_name: model_name.to_string(),
+            tag: tag.to_string(),
             layer_execution_results: LayerExecutionResult::get_profile_results(model_name, tag),
-            stage_execution_results: DashMap::new(),
             execution_result_cache: DashMap::new(),
+            max_num_nodes: 0,
+            device_classes: Vec::new(),
+            computed: false,
         }
     }
 
-    pub fn divide_and_conquer(&mut self, max_num_nodes: u32) -> Result<(), PlannerError> {
-        if !self.stage_execution_results.is_empty() {
+    pub fn divide_and_conquer(
+        &mut self,
+        max_num_nodes: u32,
+        device_classes: Vec<DeviceClass>,
+        activation_memory_overhead: u64,
+    ) -> Result<(), PlannerError> {
+        if self.computed {
             return Ok(());
         }
 
         let num_layers = self.layer_execution_results.len();
+        let total_devices: u32 = device_classes.iter().map(|class| class.count).sum();
 
-        if max_num_nodes as usize > num_layers {
+        if max_num_nodes as usize > num_layers || max_num_nodes > total_devices {
             return Err(PlannerError::new("Invalid number of nodes"));
         }
 
+        // A layer that cannot fit on any device class can never be part of
+        // any feasible stage, so fail fast instead of letting it silently
+        // disappear behind a generic "no feasible template" error later on.
+        for (index, layer) in self.layer_execution_results.iter().enumerate() {
+            let required = layer.memory + activation_memory_overhead;
+            if !device_classes
+                .iter()
+                .any(|class| required <= class.memory_budget)
+            {
+                return Err(PlannerError::new(&format!(
+                    "Layer {} alone requires more memory than any available device class provides",
+                    index
+                )));
+            }
+        }
+
+        self.max_num_nodes = max_num_nodes;
+        self.device_classes = device_classes;
+
+        let profile_hash = Self::profile_hash(
+            &self.model_name,
+            &self.tag,
+            max_num_nodes,
+            &self.device_classes,
+            activation_memory_overhead,
+        )
+        .ok();
+        if let Some(hash) = &profile_hash {
+            if let Some(cached) = Self::load_cache(&self.model_name, &self.tag, hash, max_num_nodes)
+            {
+                if cached.num_layers == num_layers {
+                    log::debug!(
+                        "Loaded pipeline template cache for {}__{} (profile hash {})",
+                        self.model_name,
+                        self.tag,
+                        hash
+                    );
+                    for entry in cached.entries {
+                        self.execution_result_cache.insert(entry.key, entry.result);
+                    }
+                    self.computed = true;
+                    return Ok(());
+                }
+            }
+        }
+
         // Put all base cases in the cache
         (0..num_layers).into_par_iter().for_each(|i| {
             ((i + 1)..=num_layers).into_par_iter().for_each(|j| {
-                let stage_execution_result = Arc::new(StageExecutionResult::new(
-                    &self.layer_execution_results[i..j],
-                ));
+                let memory_used: u64 = self.layer_execution_results[i..j]
+                    .iter()
+                    .map(|layer| layer.memory)
+                    .sum::<u64>()
+                    + activation_memory_overhead;
+
+                let stage_execution_result =
+                    StageExecutionResult::new(&self.layer_execution_results[i..j]);
+                let stage_latency = stage_execution_result.latency();
                 log::debug!(
                     "StageExecutionResult({}, {})  -> {}",
                     stage_execution_result.layers.0,
                     stage_execution_result.layers.1,
-                    stage_execution_result.latency()
+                    stage_latency
                 );
-                self.stage_execution_results
-                    .insert((i, j), stage_execution_result.clone());
 
-                let pipeline_execution_result =
-                    PipelineExecutionResult::make_base_result(stage_execution_result);
-                log::debug!(
-                    "PipelineExecutionResult({}, {}, {}) -> {}",
-                    1,
-                    i,
-                    j,
-                    pipeline_execution_result.latency()
-                );
+                let mut candidates: Vec<DeviceAwareResult> = Vec::new();
+                for (class_index, class) in self.device_classes.iter().enumerate() {
+                    if memory_used > class.memory_budget || class.count < 1 {
+                        continue;
+                    }
+                    let mut device_usage = vec![0u32; self.device_classes.len()];
+                    device_usage[class_index] = 1;
+                    candidates.push(DeviceAwareResult {
+                        stage_boundaries: vec![(i, j)],
+                        stage_device_classes: vec![class_index],
+                        stage_latencies: vec![stage_latency],
+                        device_usage,
+                        effective_latency: stage_latency * class.compute_multiplier,
+                    });
+                }
+
+                if candidates.is_empty() {
+                    log::debug!(
+                        "StageExecutionResult({}, {}) -> exceeds device memory ({} bytes)",
+                        i,
+                        j,
+                        memory_used
+                    );
+                    self.execution_result_cache
+                        .insert((1, i, j), Err("Stage exceeds device memory".to_string()));
+                    return;
+                }
+
                 self.execution_result_cache
-                    .insert((1, i, j), Ok(pipeline_execution_result));
+                    .insert((1, i, j), Ok(candidates));
             });
         });
 
@@ -87,11 +369,10 @@ impl PipelineTemplateGenerator {
                     }
 
                     // Spawn a task to compute the result for this subproblem.
-                    let best_result = (i..j)
+                    let candidates: Vec<DeviceAwareResult> = (i..j)
                         .into_par_iter()
-                        .map(|num_layers_left| {
-                            let mut result: Result<PipelineExecutionResult, String> =
-                                Err("Error in subproblem".to_string());
+                        .flat_map_iter(|num_layers_left| {
+                            let mut split_candidates: Vec<DeviceAwareResult> = Vec::new();
 
                             for num_stages_left in 1..num_stages {
                                 let num_stages_right = num_stages - num_stages_left;
@@ -115,84 +396,520 @@ impl PipelineTemplateGenerator {
                                     continue;
                                 }
 
-                                // Merge two subproblems into a bigger PipelineExecutionResult
-                                let local_result = PipelineExecutionResult::new(
-                                    left.as_ref().unwrap(),
-                                    right.as_ref().unwrap(),
-                                );
-                                if result.is_err()
-                                    || local_result.cmp(result.as_ref().unwrap()) == Ordering::Less
-                                {
-                                    result = Ok(local_result);
+                                // Merge every left/right device-usage pair whose
+                                // combined consumption is realizable from the pool.
+                                for left_candidate in left.as_ref().unwrap() {
+                                    for right_candidate in right.as_ref().unwrap() {
+                                        let device_usage: Vec<u32> = left_candidate
+                                            .device_usage
+                                            .iter()
+                                            .zip(right_candidate.device_usage.iter())
+                                            .map(|(l, r)| l + r)
+                                            .collect();
+
+                                        if !usage_fits_pool(&device_usage, &self.device_classes) {
+                                            continue;
+                                        }
+
+                                        let mut stage_boundaries =
+                                            left_candidate.stage_boundaries.clone();
+                                        stage_boundaries
+                                            .extend(right_candidate.stage_boundaries.iter());
+
+                                        let mut stage_device_classes =
+                                            left_candidate.stage_device_classes.clone();
+                                        stage_device_classes
+                                            .extend(right_candidate.stage_device_classes.iter());
+
+                                        let mut stage_latencies =
+                                            left_candidate.stage_latencies.clone();
+                                        stage_latencies
+                                            .extend(right_candidate.stage_latencies.iter());
+
+                                        split_candidates.push(DeviceAwareResult {
+                                            stage_boundaries,
+                                            stage_device_classes,
+                                            stage_latencies,
+                                            device_usage,
+                                            // The pipeline's steady-state throughput is
+                                            // bottlenecked by its slowest stage.
+                                            effective_latency: left_candidate
+                                                .effective_latency
+                                                .max(right_candidate.effective_latency),
+                                        });
+                                    }
                                 }
                             }
 
-                            result
+                            split_candidates
                         })
-                        .reduce(
-                            || Err("Error in subproblem".to_string()),
-                            |acc, result| {
-                                if result.is_err() {
-                                    return acc;
-                                } else if acc.is_err() {
-                                    return result;
-                                } else if result.as_ref().unwrap() < acc.as_ref().unwrap() {
-                                    return result;
-                                } else {
-                                    return acc;
-                                }
-                            },
-                        );
+                        .collect();
+
+                    let candidates = prune_dominated(best_per_device_usage(candidates));
 
                     log::debug!(
-                        "PipelineExecutionResult({}, {}, {}) -> {}",
+                        "PipelineExecutionResult({}, {}, {}) -> {} candidate(s)",
                         num_stages,
                         i,
                         j,
-                        if best_result.is_ok() {
-                            best_result.as_ref().unwrap().latency()
+                        candidates.len()
+                    );
+
+                    self.execution_result_cache.insert(
+                        key,
+                        if candidates.is_empty() {
+                            Err("Infeasible case".to_string())
                         } else {
-                            0.0
-                        }
+                            Ok(candidates)
+                        },
                     );
-                    self.execution_result_cache.insert(key, best_result);
                 })
             });
         }
+
+        self.computed = true;
+
+        if let Some(hash) = &profile_hash {
+            if let Err(err) = self.save_cache(hash) {
+                log::debug!(
+                    "Failed to persist pipeline template cache: {}",
+                    err.to_string()
+                );
+            }
+        }
+
         Ok(())
     }
 
-    pub fn get_pipeline_template(&self, num_nodes: u32) -> Result<Vec<Vec<String>>, PlannerError> {
+    pub fn get_pipeline_template(
+        &self,
+        num_nodes: u32,
+    ) -> Result<Vec<StageAssignment>, PlannerError> {
+        let best = self.best_candidate(num_nodes)?;
+        Ok(Self::stage_assignments(
+            &best,
+            &self.layer_execution_results,
+        ))
+    }
+
+    /// Returns a precomputed, ordered ladder of fallback templates for
+    /// `current_num_nodes - 1, current_num_nodes - 2, ..., 1`, each read
+    /// straight out of `execution_result_cache` without recomputing the DP.
+    /// A failure handler can walk the ladder to pick the next-best pipeline
+    /// once a node is lost, without waiting for `divide_and_conquer` to run
+    /// again. Node counts with no feasible template (e.g. pruned by the
+    /// memory budget) are omitted rather than failing the whole call.
+    pub fn get_fallback_templates(
+        &self,
+        current_num_nodes: u32,
+    ) -> Result<Vec<FallbackTemplate>, PlannerError> {
+        if current_num_nodes > self.max_num_nodes {
+            return Err(PlannerError::new(&format!(
+                "No templates were computed for {} node(s); divide_and_conquer only covers up to {}",
+                current_num_nodes, self.max_num_nodes
+            )));
+        }
+
+        let mut fallbacks = Vec::new();
+        for num_nodes in (1..current_num_nodes).rev() {
+            match self.best_candidate(num_nodes) {
+                Ok(best) => fallbacks.push(FallbackTemplate {
+                    num_nodes,
+                    latency: best.effective_latency,
+                    stages: Self::stage_assignments(&best, &self.layer_execution_results),
+                }),
+                Err(_) => {
+                    log::debug!(
+                        "get_fallback_templates: no feasible template for {} node(s), skipping",
+                        num_nodes
+                    );
+                }
+            }
+        }
+
+        Ok(fallbacks)
+    }
+
+    fn stage_assignments(
+        best: &DeviceAwareResult,
+        layer_execution_results: &[LayerExecutionResult],
+    ) -> Vec<StageAssignment> {
+        best.modules_per_stage(layer_execution_results)
+            .into_iter()
+            .zip(best.stage_device_classes.iter())
+            .map(|(layers, device_class)| StageAssignment {
+                layers,
+                device_class: *device_class,
+            })
+            .collect()
+    }
+
+    fn best_candidate(&self, num_nodes: u32) -> Result<DeviceAwareResult, PlannerError> {
         log::debug!(
-            "get_pipeline_template({}, {}, {})",
+            "best_candidate({}, {}, {})",
             num_nodes,
             0,
             self.layer_execution_results.len()
         );
 
-        Ok(self
+        let num_layers = self.layer_execution_results.len();
+        let entry = self
             .execution_result_cache
-            .get(&(num_nodes, 0, self.layer_execution_results.len()))
-            .unwrap()
-            .as_ref()
-            .expect(format!("No template found for num_nodes {}", num_nodes).as_str())
-            .get_modules_per_stage(&self.layer_execution_results))
+            .get(&(num_nodes, 0, num_layers))
+            .unwrap_or_else(|| panic!("No template found for num_nodes {}", num_nodes));
+
+        match entry.as_ref() {
+            Ok(candidates) => Ok(candidates
+                .iter()
+                .min_by(|a, b| a.effective_latency.total_cmp(&b.effective_latency))
+                .expect("feasible entries always have at least one candidate")
+                .clone()),
+            Err(_) => {
+                let smallest_feasible = ((num_nodes + 1)..=self.max_num_nodes).find(|n| {
+                    self.execution_result_cache
+                        .get(&(*n, 0, num_layers))
+                        .map(|entry| entry.is_ok())
+                        .unwrap_or(false)
+                });
+
+                match smallest_feasible {
+                    Some(n) => Err(PlannerError::new(&format!(
+                        "No pipeline template for {} node(s) fits within the memory budget; \
+                         the smallest feasible number of nodes is {}",
+                        num_nodes, n
+                    ))),
+                    None => Err(PlannerError::new(&format!(
+                        "No pipeline template for {} node(s) fits within the memory budget, \
+                         and none of up to {} nodes does either",
+                        num_nodes, self.max_num_nodes
+                    ))),
+                }
+            }
+        }
+    }
+
+    /// Renders the template chosen for `num_nodes` as a Graphviz DOT graph:
+    /// one cluster subgraph per stage, labeled with its index, layer names
+    /// and latency, with edges between consecutive stages labeled with the
+    /// profiled `memory` of the last layer before the stage boundary; no
+    /// separate activation size is measured for the edge.
+    pub fn to_dot(&self, num_nodes: u32) -> Result<String, PlannerError> {
+        let best = self.best_candidate(num_nodes)?;
+        let layers_per_stage = best.modules_per_stage(&self.layer_execution_results);
+
+        let mut dot = String::new();
+        dot.push_str("digraph pipeline_template {\n");
+        dot.push_str("    rankdir=LR;\n");
+        dot.push_str("    compound=true;\n");
+
+        for (stage_index, layers) in layers_per_stage.iter().enumerate() {
+            let device_class = best.stage_device_classes[stage_index];
+            let latency = best.stage_latencies[stage_index]
+                * self.device_classes[device_class].compute_multiplier;
+
+            dot.push_str(&format!("    subgraph cluster_stage{} {{\n", stage_index));
+            dot.push_str(&format!(
+                "        label=\"stage {} (latency={:.3}, device_class={})\";\n",
+                stage_index, latency, device_class
+            ));
+            for layer in layers {
+                dot.push_str(&format!("        \"{}\";\n", dot_escape(layer)));
+            }
+            dot.push_str("    }\n");
+        }
+
+        for stage_index in 1..layers_per_stage.len() {
+            let prev_last_layer = layers_per_stage[stage_index - 1]
+                .last()
+                .expect("a stage always contains at least one layer");
+            let next_first_layer = layers_per_stage[stage_index]
+                .first()
+                .expect("a stage always contains at least one layer");
+            let prev_end = best.stage_boundaries[stage_index - 1].1;
+            let communication_volume = self.layer_execution_results[prev_end - 1].memory;
+
+            dot.push_str(&format!(
+                "    \"{}\" -> \"{}\" [ltail=cluster_stage{}, lhead=cluster_stage{}, label=\"{} bytes\"];\n",
+                dot_escape(prev_last_layer),
+                dot_escape(next_first_layer),
+                stage_index - 1,
+                stage_index,
+                communication_volume
+            ));
+        }
+
+        dot.push_str("}\n");
+        Ok(dot)
+    }
+
+    /// Lists the versions of the on-disk template cache available for
+    /// `model_name`/`tag`, sorted from oldest to newest, with each version's
+    /// source profile hash and the node-count it was computed up to. Reads
+    /// only each version's small `.meta.json` sidecar, never the full
+    /// `PersistedTemplateCache` payload, so this stays cheap regardless of
+    /// how large the cached DP table is.
+    pub fn list_cached_versions(model_name: &str, tag: &str) -> Vec<CachedVersionInfo> {
+        let dir = Self::profiles_dir();
+        let prefix = Self::cache_file_prefix(model_name, tag);
+
+        let Ok(read_dir) = fs::read_dir(&dir) else {
+            return Vec::new();
+        };
+
+        let mut versions: Vec<CachedVersionInfo> = read_dir
+            .flatten()
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_string_lossy().into_owned();
+                let version_str = file_name
+                    .strip_prefix(&prefix)?
+                    .strip_suffix(".meta.json")?
+                    .to_string();
+                version_str.parse::<u32>().ok()?;
+                let contents = fs::read_to_string(entry.path()).ok()?;
+                serde_json::from_str::<CachedVersionInfo>(&contents).ok()
+            })
+            .collect();
+
+        versions.sort_by_key(|version| version.version);
+        versions
+    }
+
+    fn profiles_dir() -> PathBuf {
+        PathBuf::from(
+            std::env::var("OOBLECK_BASE_DIR").unwrap_or_else(|_| "/tmp/oobleck".to_string()),
+        )
+        .join("profiles")
+    }
+
+    fn cache_file_prefix(model_name: &str, tag: &str) -> String {
+        format!("{}__{}.plan_cache.v", model_name, tag)
+    }
+
+    fn cache_file_path(model_name: &str, tag: &str, version: u32) -> PathBuf {
+        Self::profiles_dir().join(format!(
+            "{}{}.json",
+            Self::cache_file_prefix(model_name, tag),
+            version
+        ))
+    }
+
+    // Lightweight sidecar next to `cache_file_path`, holding just a
+    // `CachedVersionInfo` so `list_cached_versions` never has to parse the
+    // full (potentially huge) cache payload to compare versions.
+    fn meta_file_path(model_name: &str, tag: &str, version: u32) -> PathBuf {
+        Self::profiles_dir().join(format!(
+            "{}{}.meta.json",
+            Self::cache_file_prefix(model_name, tag),
+            version
+        ))
+    }
+
+    // Hashes everything that determines the contents of `execution_result_cache`:
+    // the profile CSV itself, `max_num_nodes`, the device-class pool, and the
+    // activation memory overhead. Any change to these must invalidate the cache.
+    fn profile_hash(
+        model_name: &str,
+        tag: &str,
+        max_num_nodes: u32,
+        device_classes: &[DeviceClass],
+        activation_memory_overhead: u64,
+    ) -> Result<String, PlannerError> {
+        let path = Self::profiles_dir().join(format!("{}__{}.csv", model_name, tag));
+        let contents = fs::read(&path).map_err(|err| {
+            PlannerError::new(&format!(
+                "Failed to read profile file {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+
+        let mut hasher = DefaultHasher::new();
+        contents.hash(&mut hasher);
+        max_num_nodes.hash(&mut hasher);
+        activation_memory_overhead.hash(&mut hasher);
+        for class in device_classes {
+            class.compute_multiplier.to_bits().hash(&mut hasher);
+            class.memory_budget.hash(&mut hasher);
+            class.count.hash(&mut hasher);
+        }
+        Ok(format!("{:016x}", hasher.finish()))
+    }
+
+    fn load_cache(
+        model_name: &str,
+        tag: &str,
+        profile_hash: &str,
+        max_num_nodes: u32,
+    ) -> Option<PersistedTemplateCache> {
+        // `profile_hash` already folds `max_num_nodes` in, so two versions can
+        // only share a hash if they were computed for the same node count —
+        // this is an exact match, not a "large enough" lookup.
+        let version = Self::list_cached_versions(model_name, tag)
+            .into_iter()
+            .filter(|version| {
+                version.profile_hash == profile_hash && version.max_num_nodes == max_num_nodes
+            })
+            .max_by_key(|version| version.version)?;
+
+        let path = Self::cache_file_path(model_name, tag, version.version);
+        let contents = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    fn save_cache(&self, profile_hash: &str) -> Result<(), PlannerError> {
+        let dir = Self::profiles_dir();
+        fs::create_dir_all(&dir)
+            .map_err(|err| PlannerError::new(&format!("Failed to create profiles dir: {}", err)))?;
+
+        let mut versions = Self::list_cached_versions(&self.model_name, &self.tag);
+        let next_version = versions
+            .iter()
+            .map(|version| version.version)
+            .max()
+            .unwrap_or(0)
+            + 1;
+
+        let cache = PersistedTemplateCache {
+            profile_hash: profile_hash.to_string(),
+            max_num_nodes: self.max_num_nodes,
+            num_layers: self.layer_execution_results.len(),
+            device_classes: self.device_classes.clone(),
+            entries: self
+                .execution_result_cache
+                .iter()
+                .map(|entry| PersistedCacheEntry {
+                    key: *entry.key(),
+                    result: entry.value().clone(),
+                })
+                .collect(),
+        };
+
+        let path = Self::cache_file_path(&self.model_name, &self.tag, next_version);
+        let serialized = serde_json::to_string(&cache).map_err(|err| {
+            PlannerError::new(&format!("Failed to serialize template cache: {}", err))
+        })?;
+        fs::write(&path, serialized).map_err(|err| {
+            PlannerError::new(&format!(
+                "Failed to write template cache to {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+
+        let meta = CachedVersionInfo {
+            version: next_version,
+            profile_hash: profile_hash.to_string(),
+            max_num_nodes: self.max_num_nodes,
+        };
+        let meta_path = Self::meta_file_path(&self.model_name, &self.tag, next_version);
+        let serialized_meta = serde_json::to_string(&meta).map_err(|err| {
+            PlannerError::new(&format!(
+                "Failed to serialize template cache metadata: {}",
+                err
+            ))
+        })?;
+        fs::write(&meta_path, serialized_meta).map_err(|err| {
+            PlannerError::new(&format!(
+                "Failed to write template cache metadata to {}: {}",
+                meta_path.display(),
+                err
+            ))
+        })?;
+
+        versions.push(meta);
+        versions.sort_by_key(|version| version.version);
+        while versions.len() > MAX_CACHED_TEMPLATE_VERSIONS {
+            let oldest = versions.remove(0);
+            let _ = fs::remove_file(Self::cache_file_path(
+                &self.model_name,
+                &self.tag,
+                oldest.version,
+            ));
+            let _ = fs::remove_file(Self::meta_file_path(
+                &self.model_name,
+                &self.tag,
+                oldest.version,
+            ));
+        }
+
+        Ok(())
     }
 }
 
+// Python-facing surface for callers that need to hold a generator across
+// calls, e.g. a runtime that calls `get_fallback_templates` again whenever a
+// node is lost rather than re-running `divide_and_conquer` from scratch.
+#[pymethods]
+impl PipelineTemplateGenerator {
+    #[new]
+    fn py_new(model_name: &str, tag: &str) -> Self {
+        Self::new(model_name, tag)
+    }
+
+    #[pyo3(name = "divide_and_conquer")]
+    fn py_divide_and_conquer(
+        &mut self,
+        max_num_nodes: u32,
+        device_classes: Vec<DeviceClass>,
+        activation_memory_overhead: u64,
+    ) -> Result<(), PlannerError> {
+        self.divide_and_conquer(max_num_nodes, device_classes, activation_memory_overhead)
+    }
+
+    #[pyo3(name = "get_pipeline_template")]
+    fn py_get_pipeline_template(
+        &self,
+        num_nodes: u32,
+    ) -> Result<Vec<StageAssignment>, PlannerError> {
+        self.get_pipeline_template(num_nodes)
+    }
+
+    #[pyo3(name = "get_fallback_templates")]
+    fn py_get_fallback_templates(
+        &self,
+        current_num_nodes: u32,
+    ) -> Result<Vec<FallbackTemplate>, PlannerError> {
+        self.get_fallback_templates(current_num_nodes)
+    }
+
+    #[pyo3(name = "to_dot")]
+    fn py_to_dot(&self, num_nodes: u32) -> Result<String, PlannerError> {
+        self.to_dot(num_nodes)
+    }
+}
+
+/// One rung of the fallback ladder returned by `get_fallback_templates`: the
+/// best known pipeline for `num_nodes` nodes, its latency, and its stages.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct FallbackTemplate {
+    #[pyo3(get)]
+    pub num_nodes: u32,
+    #[pyo3(get)]
+    pub latency: f64,
+    #[pyo3(get)]
+    pub stages: Vec<StageAssignment>,
+}
+
 #[pyfunction]
 pub fn create_pipeline_templates(
     model_name: &str,
     tag: &str,
     mut nodes: Vec<u32>,
-) -> Result<HashMap<u32, Vec<Vec<String>>>, PlannerError> {
+    device_classes: Vec<DeviceClass>,
+    activation_memory_overhead: u64,
+) -> Result<HashMap<u32, Vec<StageAssignment>>, PlannerError> {
     let _ = env_logger::try_init();
     nodes.sort();
 
     let mut generator = PipelineTemplateGenerator::new(model_name, tag);
-    generator.divide_and_conquer(nodes[nodes.len() - 1])?;
+    generator.divide_and_conquer(
+        nodes[nodes.len() - 1],
+        device_classes,
+        activation_memory_overhead,
+    )?;
 
-    let mut results: HashMap<u32, Vec<Vec<String>>> = HashMap::new();
+    let mut results: HashMap<u32, Vec<StageAssignment>> = HashMap::new();
     for num_node in nodes {
         let template = generator.get_pipeline_template(num_node)?;
         results.insert(num_node, template);
@@ -201,6 +918,22 @@ pub fn create_pipeline_templates(
     Ok(results)
 }
 
+#[pyfunction]
+pub fn pipeline_template_to_dot(
+    model_name: &str,
+    tag: &str,
+    max_num_nodes: u32,
+    num_nodes: u32,
+    device_classes: Vec<DeviceClass>,
+    activation_memory_overhead: u64,
+) -> Result<String, PlannerError> {
+    let _ = env_logger::try_init();
+
+    let mut generator = PipelineTemplateGenerator::new(model_name, tag);
+    generator.divide_and_conquer(max_num_nodes, device_classes, activation_memory_overhead)?;
+    generator.to_dot(num_nodes)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -208,8 +941,10 @@ mod test {
     use std::path::PathBuf;
 
     fn prepare_profile_file(num_layers: u32, same_latency: bool) {
-        let model_name = "gpt2";
-        let tag = "test";
+        prepare_profile_file_for("gpt2", "test", num_layers, same_latency);
+    }
+
+    fn prepare_profile_file_for(model_name: &str, tag: &str, num_layers: u32, same_latency: bool) {
         let path =
             PathBuf::from("/tmp/oobleck/profiles/".to_string() + model_name + "__" + tag + ".csv");
         fs::create_dir_all(path.parent().unwrap()).unwrap();
@@ -242,26 +977,52 @@ mod test {
         writer.flush().unwrap();
     }
 
+    fn homogeneous_device_classes(count: u32) -> Vec<DeviceClass> {
+        vec![DeviceClass {
+            compute_multiplier: 1.0,
+            memory_budget: u64::MAX,
+            count,
+        }]
+    }
+
+    fn layers_only(
+        templates: &HashMap<u32, Vec<StageAssignment>>,
+        num_nodes: u32,
+    ) -> Vec<Vec<String>> {
+        templates[&num_nodes]
+            .iter()
+            .map(|stage| stage.layers.clone())
+            .collect()
+    }
+
     #[test]
     fn test_return_no_template_for_too_large_num_nodes() {
         prepare_profile_file(6, true);
 
-        let templates = create_pipeline_templates("gpt2", "test", vec![7]);
+        let templates =
+            create_pipeline_templates("gpt2", "test", vec![7], homogeneous_device_classes(7), 0);
         assert!(templates.is_err());
     }
 
     #[test]
     fn test_all_layers_covered() {
         prepare_profile_file(6, false);
-        let templates = create_pipeline_templates("gpt2", "test", vec![1, 2, 3, 4, 5, 6]).unwrap();
+        let templates = create_pipeline_templates(
+            "gpt2",
+            "test",
+            vec![1, 2, 3, 4, 5, 6],
+            homogeneous_device_classes(6),
+            0,
+        )
+        .unwrap();
 
         let expected_layers: Vec<String> = (0..6).map(|i| format!("layer{}", i)).collect();
 
-        for (_, template) in templates.iter() {
+        for num_nodes in templates.keys() {
             let mut covered_layers: Vec<String> = Vec::new();
-            for stage in template.iter() {
-                for layer in stage.iter() {
-                    covered_layers.push(layer.clone());
+            for stage in layers_only(&templates, *num_nodes) {
+                for layer in stage {
+                    covered_layers.push(layer);
                 }
             }
             assert_eq!(covered_layers, expected_layers);
@@ -271,49 +1032,286 @@ mod test {
     #[test]
     fn test_divide_and_conquer_base_only() {
         prepare_profile_file(6, false);
-        let template = create_pipeline_templates("gpt2", "test", vec![1]).unwrap();
-        assert_eq!(template.len(), 1);
-        assert_eq!(template[&1].len(), 1);
+        let templates =
+            create_pipeline_templates("gpt2", "test", vec![1], homogeneous_device_classes(1), 0)
+                .unwrap();
+        assert_eq!(templates.len(), 1);
         assert_eq!(
-            template[&1][0],
-            vec!["layer0", "layer1", "layer2", "layer3", "layer4", "layer5"]
+            layers_only(&templates, 1),
+            vec![vec![
+                "layer0", "layer1", "layer2", "layer3", "layer4", "layer5"
+            ]]
         );
     }
 
     #[test]
     fn test_divide_and_conquer_divide() {
         prepare_profile_file(6, false);
-        let templates = create_pipeline_templates("gpt2", "test", vec![1, 2]).unwrap();
+        let templates =
+            create_pipeline_templates("gpt2", "test", vec![1, 2], homogeneous_device_classes(2), 0)
+                .unwrap();
         assert_eq!(templates.len(), 2);
         assert_eq!(
-            templates[&1][0],
-            vec!["layer0", "layer1", "layer2", "layer3", "layer4", "layer5"]
+            layers_only(&templates, 1),
+            vec![vec![
+                "layer0", "layer1", "layer2", "layer3", "layer4", "layer5"
+            ]]
         );
+        let two_node_template = layers_only(&templates, 2);
         assert_eq!(
-            templates[&2][0],
+            two_node_template[0],
             vec!["layer0", "layer1", "layer2", "layer3"]
         );
-        assert_eq!(templates[&2][1], vec!["layer4", "layer5"]);
+        assert_eq!(two_node_template[1], vec!["layer4", "layer5"]);
     }
 
     #[test]
     fn test_divide_and_conquer_divide2() {
         prepare_profile_file(6, false);
-        let templates = create_pipeline_templates("gpt2", "test", vec![2, 3, 4]).unwrap();
+        let templates = create_pipeline_templates(
+            "gpt2",
+            "test",
+            vec![2, 3, 4],
+            homogeneous_device_classes(4),
+            0,
+        )
+        .unwrap();
         assert_eq!(templates.len(), 3);
+
+        let two_node_template = layers_only(&templates, 2);
         assert_eq!(
-            templates[&2][0],
+            two_node_template[0],
             vec!["layer0", "layer1", "layer2", "layer3"]
         );
-        assert_eq!(templates[&2][1], vec!["layer4", "layer5"]);
+        assert_eq!(two_node_template[1], vec!["layer4", "layer5"]);
+
+        let three_node_template = layers_only(&templates, 3);
+        assert_eq!(three_node_template[0], vec!["layer0", "layer1", "layer2"]);
+        assert_eq!(three_node_template[1], vec!["layer3", "layer4"]);
+        assert_eq!(three_node_template[2], vec!["layer5"]);
+
+        let four_node_template = layers_only(&templates, 4);
+        assert_eq!(four_node_template[0], vec!["layer0", "layer1", "layer2"]);
+        assert_eq!(four_node_template[1], vec!["layer3"]);
+        assert_eq!(four_node_template[2], vec!["layer4"]);
+        assert_eq!(four_node_template[3], vec!["layer5"]);
+    }
+
+    #[test]
+    fn test_memory_constrained_partitioning_prunes_infeasible_stages() {
+        // Each layer costs 1 unit of memory, so 1 node cannot hold all 6
+        // layers, but 2 nodes (3 layers each, budget 3) can.
+        prepare_profile_file(6, true);
+
+        let tight_budget = vec![DeviceClass {
+            compute_multiplier: 1.0,
+            memory_budget: 3,
+            count: 1,
+        }];
+        assert!(create_pipeline_templates("gpt2", "test", vec![1], tight_budget, 0).is_err());
+
+        let tight_budget = vec![DeviceClass {
+            compute_multiplier: 1.0,
+            memory_budget: 3,
+            count: 2,
+        }];
+        let templates =
+            create_pipeline_templates("gpt2", "test", vec![2], tight_budget, 0).unwrap();
+        let two_node_template = layers_only(&templates, 2);
+        assert_eq!(two_node_template[0], vec!["layer0", "layer1", "layer2"]);
+        assert_eq!(two_node_template[1], vec!["layer3", "layer4", "layer5"]);
+    }
+
+    #[test]
+    fn test_memory_constrained_partitioning_reports_smallest_feasible_num_nodes() {
+        prepare_profile_file(6, true);
+
+        let tight_budget = vec![DeviceClass {
+            compute_multiplier: 1.0,
+            memory_budget: 3,
+            count: 2,
+        }];
+        let err =
+            create_pipeline_templates("gpt2", "test", vec![1, 2], tight_budget, 0).unwrap_err();
+        assert!(err.to_string().contains('2'));
+    }
+
+    #[test]
+    fn test_single_layer_exceeding_memory_budget_is_reported() {
+        prepare_profile_file(6, true);
+
+        let no_budget = vec![DeviceClass {
+            compute_multiplier: 1.0,
+            memory_budget: 0,
+            count: 6,
+        }];
+        let templates = create_pipeline_templates("gpt2", "test", vec![6], no_budget, 0);
+        assert!(templates.is_err());
+    }
+
+    #[test]
+    fn test_heterogeneous_device_classes_are_assigned_and_scale_latency() {
+        // A slow class and a fast class, one device each, both with room for
+        // all 6 layers; each layer takes 1 unit of memory.
+        prepare_profile_file(6, true);
+
+        let device_classes = vec![
+            DeviceClass {
+                compute_multiplier: 2.0,
+                memory_budget: 6,
+                count: 1,
+            },
+            DeviceClass {
+                compute_multiplier: 1.0,
+                memory_budget: 6,
+                count: 1,
+            },
+        ];
+        let templates =
+            create_pipeline_templates("gpt2", "test", vec![2], device_classes, 0).unwrap();
+
+        let stages = &templates[&2];
+        assert_eq!(stages.len(), 2);
+        let covered_layers: Vec<String> = stages
+            .iter()
+            .flat_map(|stage| stage.layers.clone())
+            .collect();
+        assert_eq!(
+            covered_layers,
+            (0..6).map(|i| format!("layer{}", i)).collect::<Vec<_>>()
+        );
+        // Both available device classes should be used, never the same one twice.
+        assert_ne!(stages[0].device_class, stages[1].device_class);
+        // With every layer equally fast, the 2x-slower class 0 balances the
+        // pipeline by taking 2 layers while the fast class takes 4.
+        let slow_stage = stages.iter().find(|stage| stage.device_class == 0).unwrap();
+        assert_eq!(slow_stage.layers.len(), 2);
+    }
+
+    #[test]
+    fn test_get_fallback_templates_returns_descending_ladder() {
+        prepare_profile_file(6, false);
+
+        let mut generator = PipelineTemplateGenerator::new("gpt2", "test");
+        generator
+            .divide_and_conquer(4, homogeneous_device_classes(4), 0)
+            .unwrap();
+
+        let fallbacks = generator.get_fallback_templates(4).unwrap();
+        let num_nodes: Vec<u32> = fallbacks.iter().map(|f| f.num_nodes).collect();
+        assert_eq!(num_nodes, vec![3, 2, 1]);
+
+        // Each rung should cover every layer and match get_pipeline_template.
+        for fallback in &fallbacks {
+            let expected = generator.get_pipeline_template(fallback.num_nodes).unwrap();
+            assert_eq!(fallback.stages.len(), expected.len());
+            for (stage, expected_stage) in fallback.stages.iter().zip(expected.iter()) {
+                assert_eq!(stage.layers, expected_stage.layers);
+            }
+            let covered_layers: Vec<String> = fallback
+                .stages
+                .iter()
+                .flat_map(|stage| stage.layers.clone())
+                .collect();
+            assert_eq!(
+                covered_layers,
+                (0..6).map(|i| format!("layer{}", i)).collect::<Vec<_>>()
+            );
+            assert!(fallback.latency >= 0.0);
+        }
+    }
+
+    #[test]
+    fn test_get_fallback_templates_rejects_uncomputed_node_count() {
+        prepare_profile_file(6, false);
 
-        assert_eq!(templates[&3][0], vec!["layer0", "layer1", "layer2"]);
-        assert_eq!(templates[&3][1], vec!["layer3", "layer4"]);
-        assert_eq!(templates[&3][2], vec!["layer5"]);
+        let mut generator = PipelineTemplateGenerator::new("gpt2", "test");
+        generator
+            .divide_and_conquer(2, homogeneous_device_classes(2), 0)
+            .unwrap();
+
+        assert!(generator.get_fallback_templates(3).is_err());
+    }
+
+    #[test]
+    fn test_to_dot_contains_stage_clusters_and_inter_stage_edges() {
+        prepare_profile_file(6, false);
+
+        let dot = pipeline_template_to_dot("gpt2", "test", 2, 2, homogeneous_device_classes(2), 0)
+            .unwrap();
+
+        assert!(dot.starts_with("digraph pipeline_template {"));
+        assert!(dot.contains("subgraph cluster_stage0"));
+        assert!(dot.contains("subgraph cluster_stage1"));
+        assert!(dot.contains("\"layer0\""));
+        assert!(dot.contains("\"layer5\""));
+        assert!(dot.contains("ltail=cluster_stage0, lhead=cluster_stage1"));
+    }
+
+    fn clear_cache_files(model_name: &str, tag: &str) {
+        let prefix = PipelineTemplateGenerator::cache_file_prefix(model_name, tag);
+        let Ok(read_dir) = fs::read_dir(PipelineTemplateGenerator::profiles_dir()) else {
+            return;
+        };
+        for entry in read_dir.flatten() {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    #[test]
+    fn test_divide_and_conquer_reuses_on_disk_cache() {
+        let model_name = "gpt2-cache-reuse";
+        let tag = "test";
+        clear_cache_files(model_name, tag);
+        prepare_profile_file_for(model_name, tag, 6, false);
+
+        let mut first = PipelineTemplateGenerator::new(model_name, tag);
+        first
+            .divide_and_conquer(2, homogeneous_device_classes(2), 0)
+            .unwrap();
+        let expected = first.get_pipeline_template(2).unwrap();
+
+        let versions = PipelineTemplateGenerator::list_cached_versions(model_name, tag);
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0].max_num_nodes, 2);
+
+        // A fresh generator for the same profile should load the cached
+        // plan rather than recomputing the DP from scratch.
+        let mut second = PipelineTemplateGenerator::new(model_name, tag);
+        second
+            .divide_and_conquer(2, homogeneous_device_classes(2), 0)
+            .unwrap();
+        let reused = second.get_pipeline_template(2).unwrap();
+
+        assert_eq!(reused.len(), expected.len());
+        for (a, b) in reused.iter().zip(expected.iter()) {
+            assert_eq!(a.layers, b.layers);
+        }
+
+        // Still only one version on disk since nothing about the plan changed.
+        assert_eq!(
+            PipelineTemplateGenerator::list_cached_versions(model_name, tag).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_template_cache_keeps_only_last_n_versions() {
+        let model_name = "gpt2-cache-versions";
+        let tag = "test";
+        clear_cache_files(model_name, tag);
+        prepare_profile_file_for(model_name, tag, 6, false);
+
+        for activation_overhead in 0..(MAX_CACHED_TEMPLATE_VERSIONS as u64 + 2) {
+            let mut generator = PipelineTemplateGenerator::new(model_name, tag);
+            generator
+                .divide_and_conquer(2, homogeneous_device_classes(2), activation_overhead)
+                .unwrap();
+        }
 
-        assert_eq!(templates[&4][0], vec!["layer0", "layer1", "layer2"]);
-        assert_eq!(templates[&4][1], vec!["layer3"]);
-        assert_eq!(templates[&4][2], vec!["layer4"]);
-        assert_eq!(templates[&4][3], vec!["layer5"]);
+        let versions = PipelineTemplateGenerator::list_cached_versions(model_name, tag);
+        assert_eq!(versions.len(), MAX_CACHED_TEMPLATE_VERSIONS);
     }
 }